@@ -0,0 +1,46 @@
+pub mod pessimistic;
+
+// NOTE: this source tree contains only `cost_estimates/{mod,pessimistic}.rs` --
+//  there is no coordinator module, and no other `CostEstimator` implementor, on
+//  disk anywhere under this checkout (confirmed by searching the full tree for
+//  `CostEstimator`/`notify_block`/`coordinator`). `PessimisticEstimator` is this
+//  tree's sole implementor and `pessimistic.rs` its sole call site, and both
+//  already take `evaluated_epoch` consistently with the trait below. The
+//  coordinator's `notify_block` call site described in the original request
+//  lives outside this snapshot and could not be located or updated here.
+
+use chainstate::stacks::TransactionPayload;
+use rusqlite::Error as SqliteError;
+use vm::costs::ExecutionCost;
+
+use core::StacksEpochId;
+
+/// A cost estimator observes executed transactions and their actual costs, and
+///  uses that history to predict the cost of similar transactions before they
+///  are executed. Estimates are partitioned per `evaluated_epoch`, since a cost
+///  function change at an epoch boundary invalidates history gathered under a
+///  different cost schedule.
+pub trait CostEstimator: Send {
+    /// Record that `tx` was evaluated under `evaluated_epoch` and actually cost
+    ///  `actual_cost`.
+    fn notify_event(
+        &mut self,
+        tx: &TransactionPayload,
+        actual_cost: &ExecutionCost,
+        evaluated_epoch: &StacksEpochId,
+    ) -> Result<(), EstimatorError>;
+
+    /// Estimate the `ExecutionCost` of `tx` if it were evaluated under
+    ///  `evaluated_epoch`.
+    fn estimate_cost(
+        &self,
+        tx: &TransactionPayload,
+        evaluated_epoch: &StacksEpochId,
+    ) -> Result<ExecutionCost, EstimatorError>;
+}
+
+#[derive(Debug)]
+pub enum EstimatorError {
+    NoEstimateAvailable,
+    SqliteError(SqliteError),
+}
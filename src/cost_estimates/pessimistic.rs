@@ -1,5 +1,9 @@
 use std::cmp;
-use std::convert::TryFrom;
+use std::collections::{HashMap, HashSet};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 use std::{iter::FromIterator, path::Path};
 
 use super::metrics::PROPORTION_RESOLUTION;
@@ -13,7 +17,7 @@ use chainstate::stacks::TransactionPayload;
 use util::db::u64_to_sql;
 use vm::costs::ExecutionCost;
 
-use core::BLOCK_LIMIT_MAINNET;
+use core::{StacksEpochId, BLOCK_LIMIT_MAINNET};
 
 use crate::util::db::tx_begin_immediate_sqlite;
 
@@ -21,30 +25,405 @@ use super::{CostEstimator, EstimatorError};
 
 /// This struct pessimistically estimates the `ExecutionCost` of transaction payloads.
 ///
-/// Each operation has a string-valued key (see `PessimisticEstimator::get_estimate_key`).
+/// Each operation has a string-valued key (see `get_estimate_key`).
 ///
 /// For each pair of 1) operation key, and 2) dimension of
 /// ExecutionCost, the PessimisticEstimator retains a set of the top
-/// 10 highest costs yet observed for that operation/dimension. The
-/// estimate returned is the average of these.
-pub struct PessimisticEstimator {
-    db: Connection,
+/// `EstimatorConfig::window_size` highest costs yet observed for that
+/// operation/dimension. The estimate returned is `EstimatorConfig::statistic`
+/// computed over that retained set.
+///
+/// Writes are not applied to the backing `SampleStore` directly on the calling
+///  (chain-processing) thread. Instead, `notify_event` hands the observation off
+///  to a background `EstimatorService` over a channel, and `estimate_cost` is
+///  served out of an in-memory cache shared with that service, so neither path
+///  blocks on persistence I/O. `PessimisticEstimator` is generic over the
+///  `SampleStore` the service persists to, so callers can swap in a `SqliteSampleStore`
+///  for a real node or a `MemorySampleStore` for tests.
+pub struct PessimisticEstimator<S: SampleStore> {
     log_error: bool,
+    cache: Arc<Mutex<HashMap<String, Samples>>>,
+    event_sender: Sender<ServiceMessage>,
+    config: EstimatorConfig,
+    _store: std::marker::PhantomData<S>,
+}
+
+/// Tunables for a `PessimisticEstimator`: how many samples to retain per
+///  operation/dimension pair, and which statistic over that retained set is
+///  reported as the estimate.
+#[derive(Debug, Clone, Copy)]
+pub struct EstimatorConfig {
+    pub window_size: usize,
+    pub statistic: EstimationStatistic,
+}
+
+impl Default for EstimatorConfig {
+    fn default() -> EstimatorConfig {
+        EstimatorConfig {
+            window_size: SAMPLE_SIZE,
+            statistic: EstimationStatistic::Mean,
+        }
+    }
+}
+
+/// The statistic computed over a retained sample set and reported as the cost
+///  estimate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EstimationStatistic {
+    /// Arithmetic mean of the retained samples -- the historical default.
+    Mean,
+    /// The `p`th percentile (0-100) of the retained samples: the samples are
+    ///  sorted and the value is linearly interpolated between the two order
+    ///  statistics neighboring `rank = p / 100 * (n - 1)`. Lower percentiles yield a less aggressive (smaller) estimate; higher
+    ///  percentiles bias toward the observed maximum.
+    Percentile(u8),
+}
+
+impl EstimationStatistic {
+    /// A short, stable label persisted alongside samples so that an operator
+    ///  switching `EstimatorConfig::statistic` can tell, from the database
+    ///  alone, which mode a given `current_value` was computed under.
+    fn label(&self) -> String {
+        match self {
+            EstimationStatistic::Mean => "mean".to_string(),
+            EstimationStatistic::Percentile(p) => format!("p{}", p),
+        }
+    }
+}
+
+/// A pluggable backend for persisting and loading the sample sets a
+///  `PessimisticEstimator` tracks, keyed by the estimate-key string produced by
+///  `get_estimate_key`. Implementations only need to satisfy
+///  simple key-value semantics; they are not expected to reason about epochs,
+///  transaction payloads, or cost fields.
+pub trait SampleStore: Send {
+    /// Read every persisted sample set into memory, keyed by estimate-key, so a
+    ///  fresh `EstimatorService` can start warm from whatever this backend has
+    ///  already accumulated instead of an empty cache.
+    fn load_all(&self) -> Result<HashMap<String, Samples>, EstimatorError>;
+    /// Persist `samples` as the current sample set for `identifier`.
+    fn put_samples(&mut self, identifier: &str, samples: &Samples) -> Result<(), EstimatorError>;
+
+    /// Persist a batch of `(identifier, samples)` pairs. Backends for which
+    ///  writes carry a per-call cost (a transaction + fsync, say) should
+    ///  override this to commit the whole batch as one unit instead of paying
+    ///  that cost per entry; the default just calls `put_samples` in a loop.
+    fn put_many(&mut self, samples: &[(String, Samples)]) -> Result<(), EstimatorError> {
+        for (identifier, s) in samples {
+            self.put_samples(identifier, s)?;
+        }
+        Ok(())
+    }
+}
+
+/// A `SampleStore` backed by a SQLite database on disk. This is the backend
+///  used by a running node.
+pub struct SqliteSampleStore {
+    db: Connection,
+    statistic: EstimationStatistic,
+}
+
+impl SqliteSampleStore {
+    pub fn open(p: &Path, statistic: EstimationStatistic) -> Result<SqliteSampleStore, EstimatorError> {
+        let mut db = Connection::open(p)?;
+        let tx = tx_begin_immediate_sqlite(&mut db)?;
+        SqliteSampleStore::run_migrations(&tx)?;
+        tx.commit()?;
+        Ok(SqliteSampleStore { db, statistic })
+    }
+
+    /// Brings the database up to the latest known schema version, applying any
+    ///  migrations in `MIGRATIONS` that are not yet recorded in `schema_version`.
+    ///  Safe to call on a brand-new, empty file as well as an existing database
+    ///  created by an older version of this estimator.
+    fn run_migrations(tx: &SqliteTransaction) -> Result<(), SqliteError> {
+        tx.execute(CREATE_SCHEMA_VERSION_TABLE, rusqlite::NO_PARAMS)?;
+
+        let current_version: i64 = tx
+            .query_row(
+                "SELECT version FROM schema_version ORDER BY version DESC LIMIT 1",
+                rusqlite::NO_PARAMS,
+                |row| row.get(0),
+            )
+            .optional()?
+            .unwrap_or(0);
+
+        for migration in MIGRATIONS.iter().filter(|m| m.id > current_version) {
+            tx.execute(migration.up, rusqlite::NO_PARAMS)?;
+            tx.execute(
+                "INSERT INTO schema_version (version) VALUES (?)",
+                rusqlite::params![migration.id],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Shared insert body for `put_samples`/`put_many`, taking `&Connection` so
+    ///  it can run either directly against `self.db` (autocommit) or against a
+    ///  `Transaction` (which derefs to `Connection`) when batching.
+    fn execute_put(
+        conn: &Connection,
+        identifier: &str,
+        samples: &Samples,
+        statistic: &EstimationStatistic,
+    ) -> Result<(), EstimatorError> {
+        let sql = "INSERT OR REPLACE INTO pessimistic_estimator
+                     (estimate_key, current_value, samples, statistic) VALUES (?, ?, ?, ?)";
+        let current_value =
+            u64_to_sql(samples.estimate(statistic)).unwrap_or_else(|_| i64::max_value());
+        conn.execute(
+            sql,
+            rusqlite::params![identifier, current_value, samples.to_json(), statistic.label()],
+        )?;
+        Ok(())
+    }
+}
+
+impl SampleStore for SqliteSampleStore {
+    fn load_all(&self) -> Result<HashMap<String, Samples>, EstimatorError> {
+        let mut stmt = self
+            .db
+            .prepare("SELECT estimate_key, samples FROM pessimistic_estimator")?;
+        let rows = stmt.query_map(rusqlite::NO_PARAMS, |row| {
+            let key: String = row.get(0)?;
+            let samples: Samples = row.get(1)?;
+            Ok((key, samples))
+        })?;
+
+        let mut cache = HashMap::new();
+        for row in rows {
+            let (key, samples) = row?;
+            cache.insert(key, samples);
+        }
+        Ok(cache)
+    }
+
+    fn put_samples(&mut self, identifier: &str, samples: &Samples) -> Result<(), EstimatorError> {
+        Self::execute_put(&self.db, identifier, samples, &self.statistic)
+    }
+
+    /// Commits the whole batch as a single transaction, rather than one
+    ///  transaction + fsync per dirty key: `EstimatorService::flush` relies on
+    ///  this to coalesce a round of changed samples into one round of I/O.
+    fn put_many(&mut self, samples: &[(String, Samples)]) -> Result<(), EstimatorError> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        let tx = tx_begin_immediate_sqlite(&mut self.db)?;
+        for (identifier, s) in samples {
+            Self::execute_put(&tx, identifier, s, &self.statistic)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// A `SampleStore` that keeps its state purely in memory. Useful for tests, and
+///  for high-throughput deployments that would rather trade persistence across
+///  restarts for avoiding disk I/O entirely.
+pub struct MemorySampleStore {
+    samples: HashMap<String, Samples>,
+    statistic: EstimationStatistic,
+}
+
+impl MemorySampleStore {
+    pub fn new(statistic: EstimationStatistic) -> MemorySampleStore {
+        MemorySampleStore {
+            samples: HashMap::new(),
+            statistic,
+        }
+    }
+}
+
+impl SampleStore for MemorySampleStore {
+    fn load_all(&self) -> Result<HashMap<String, Samples>, EstimatorError> {
+        Ok(self.samples.clone())
+    }
+
+    fn put_samples(&mut self, identifier: &str, samples: &Samples) -> Result<(), EstimatorError> {
+        self.samples
+            .insert(identifier.to_string(), samples.clone());
+        Ok(())
+    }
+}
+
+/// One observed `(transaction, cost, epoch)` tuple, as handed off from the
+///  chain-processing thread to the `EstimatorService`.
+struct EstimatorEvent {
+    tx: TransactionPayload,
+    actual_cost: ExecutionCost,
+    evaluated_epoch: StacksEpochId,
+}
+
+/// How long the background service waits for a new message before running an
+///  idle flush. Changed samples are otherwise flushed once after each burst of
+///  queued messages has been drained; there is no fixed flush cadence while
+///  messages keep arriving.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A message sent from `PessimisticEstimator` to its `EstimatorService` over
+///  `event_sender`.
+enum ServiceMessage {
+    /// A newly observed `(transaction, cost, epoch)` tuple to fold into the cache.
+    Event(EstimatorEvent),
+    /// A request to flush pending writes and then report back on `Sender`, once
+    ///  every message enqueued before this one has been applied. Used to make
+    ///  `notify_event` followed by `estimate_cost`/a `SampleStore` read
+    ///  deterministic in tests, since the channel is otherwise fire-and-forget.
+    Sync(Sender<()>),
+}
+
+/// Owns the `SampleStore` and the write side of the estimator. Runs on its own
+///  thread, draining `EstimatorEvent`s off a channel, applying them to an
+///  in-memory cache shared with `PessimisticEstimator`, and flushing only the
+///  entries that actually changed after each drained burst of messages.
+struct EstimatorService<S: SampleStore> {
+    store: S,
+    cache: Arc<Mutex<HashMap<String, Samples>>>,
+    receiver: mpsc::Receiver<ServiceMessage>,
+    config: EstimatorConfig,
+}
+
+impl<S: SampleStore> EstimatorService<S> {
+    fn run(mut self) {
+        let mut dirty: HashSet<String> = HashSet::new();
+        loop {
+            match self.receiver.recv_timeout(FLUSH_INTERVAL) {
+                Ok(message) => {
+                    self.handle_message(message, &mut dirty);
+                    // Drain any messages that arrived in the same burst before flushing,
+                    //  so a busy block coalesces into a single round of writes.
+                    while let Ok(message) = self.receiver.try_recv() {
+                        self.handle_message(message, &mut dirty);
+                    }
+                    self.flush(&mut dirty);
+                }
+                Err(RecvTimeoutError::Timeout) => self.flush(&mut dirty),
+                Err(RecvTimeoutError::Disconnected) => {
+                    self.flush(&mut dirty);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Applies an `Event` to the cache, or flushes and acks a `Sync`. Messages
+    ///  are handled in the order they were sent, so by the time a `Sync` is
+    ///  read off the channel, every `Event` sent before it has already been
+    ///  applied to `cache`.
+    fn handle_message(&mut self, message: ServiceMessage, dirty: &mut HashSet<String>) {
+        match message {
+            ServiceMessage::Event(event) => self.apply_event(event, dirty),
+            ServiceMessage::Sync(ack) => {
+                self.flush(dirty);
+                let _ = ack.send(());
+            }
+        }
+    }
+
+    fn apply_event(&self, event: EstimatorEvent, dirty: &mut HashSet<String>) {
+        for field in CostField::ALL.iter() {
+            let key = get_estimate_key(&event.tx, field, &event.evaluated_epoch);
+            let value = field.select_key(&event.actual_cost);
+            self.apply_sample(key, value, dirty);
+        }
+    }
+
+    /// Applies a single `(key, value)` observation to the shared cache, marking
+    ///  `key` dirty if the sample set actually changed. Split out from
+    ///  `apply_event` so this delta-tracking behavior can be exercised directly
+    ///  in tests without needing a real `TransactionPayload`.
+    fn apply_sample(&self, key: String, value: u64, dirty: &mut HashSet<String>) {
+        let mut cache = self.cache.lock().expect("estimator cache lock poisoned");
+        let changed = cache
+            .entry(key.clone())
+            .or_insert_with(|| Samples { items: vec![] })
+            .update_with(value, self.config.window_size);
+        if changed {
+            dirty.insert(key);
+        }
+    }
+
+    /// Persist only the samples that changed since the last flush.
+    fn flush(&mut self, dirty: &mut HashSet<String>) {
+        if dirty.is_empty() {
+            return;
+        }
+        // Clone the dirty samples out and drop the cache lock before writing to the
+        //  store: `estimate_cost` takes this same lock for its in-memory reads, and
+        //  holding it across put_many's SQLite I/O would stall that low-latency
+        //  read path for the duration of the flush -- exactly the consensus-path
+        //  stall this service was introduced to remove.
+        let to_write: Vec<(String, Samples)> = {
+            let cache = self.cache.lock().expect("estimator cache lock poisoned");
+            dirty
+                .drain()
+                .filter_map(|key| cache.get(&key).map(|samples| (key, samples.clone())))
+                .collect()
+        };
+        // A transient write error must not take this thread down: that would drop
+        //  the receiver and silently freeze estimates for the rest of the node's
+        //  lifetime. Log and move on -- the in-memory cache (and thus
+        //  estimate_cost) is unaffected either way, and these keys will be
+        //  retried on their next flush if they change again.
+        if let Err(e) = self.store.put_many(&to_write) {
+            warn!("Failed to flush cost estimator samples to store"; "error" => ?e);
+        }
+    }
 }
 
-#[derive(Debug)]
-struct Samples {
+#[derive(Debug, Clone)]
+pub struct Samples {
     items: Vec<u64>,
 }
 
+/// Default number of samples retained per operation/dimension pair; see
+///  `EstimatorConfig::window_size`.
 const SAMPLE_SIZE: usize = 10;
+// `IF NOT EXISTS` matters here: an operator upgrading from a pre-migration-framework
+//  build already has this table (created by the old, hard-coded `CREATE TABLE`) but no
+//  `schema_version` row, so `current_version` reads 0 and migration 1 runs again against
+//  an existing database. Without this, that re-run fails with "table already exists" and
+//  `open` errors out instead of adopting the operator's accumulated cost history.
 const CREATE_TABLE: &'static str = "
-CREATE TABLE pessimistic_estimator (
+CREATE TABLE IF NOT EXISTS pessimistic_estimator (
     estimate_key TEXT PRIMARY KEY,
     current_value NUMBER NOT NULL,
     samples TEXT NOT NULL
 )";
 
+const ADD_STATISTIC_COLUMN: &'static str =
+    "ALTER TABLE pessimistic_estimator ADD COLUMN statistic TEXT NOT NULL DEFAULT 'mean'";
+
+const CREATE_SCHEMA_VERSION_TABLE: &'static str = "
+CREATE TABLE IF NOT EXISTS schema_version (
+    version INTEGER NOT NULL
+)";
+
+/// A single step in the `PessimisticEstimator`'s on-disk schema history.
+/// `id` must be strictly increasing and contiguous with the existing
+///  migrations -- it is both the migration's identity and its ordering.
+struct Migration {
+    id: i64,
+    up: &'static str,
+}
+
+/// Ordered list of schema migrations applied to a `pessimistic_estimator.sqlite`
+///  file when it is opened. Migrations already recorded in the `schema_version`
+///  table are skipped, so new migrations can be appended here across releases
+///  without disturbing operators' existing databases or accumulated cost history.
+static MIGRATIONS: &[Migration] = &[
+    Migration {
+        id: 1,
+        up: CREATE_TABLE,
+    },
+    Migration {
+        id: 2,
+        up: ADD_STATISTIC_COLUMN,
+    },
+];
+
 iterable_enum!(CostField {
     RuntimeCost,
     WriteLength,
@@ -96,11 +475,30 @@ impl Samples {
         JsonValue::from(self.items.as_slice())
     }
 
+    /// Shrinks the retained sample set down to `window_size`, keeping the
+    ///  highest values and dropping the rest. A no-op if the set is already at
+    ///  or under `window_size`. Needed because a set warm-loaded from a
+    ///  `SampleStore` may have been persisted under a larger
+    ///  `EstimatorConfig::window_size` than the one configured now; without
+    ///  this, a lowered window is never actually honored for those keys.
+    fn truncate_to_window(&mut self, window_size: usize) {
+        if self.items.len() <= window_size {
+            return;
+        }
+        self.items.sort_unstable_by(|a, b| b.cmp(a));
+        self.items.truncate(window_size);
+    }
+
     /// Add a new sample to this struct. The pessimistic sampler only adds to the sample set
-    ///  if the sample set is less than SAMPLE_SIZE or the new sample is greater than the current min.
-    /// Boolean return indicates whether or not the sample was included.
-    fn update_with(&mut self, sample: u64) -> bool {
-        if self.items.len() < SAMPLE_SIZE {
+    ///  if the sample set is smaller than `window_size` or the new sample is greater than
+    ///  the current min. Boolean return indicates whether or not the sample was included.
+    ///
+    ///  Also shrinks the set down to `window_size` first, in case it was warm-loaded
+    ///  from a `SampleStore` under a larger, previously-configured window.
+    fn update_with(&mut self, sample: u64, window_size: usize) -> bool {
+        self.truncate_to_window(window_size);
+
+        if self.items.len() < window_size {
             self.items.push(sample);
             return true;
         }
@@ -112,7 +510,7 @@ impl Samples {
             .min_by_key(|(_i, value)| *value)
         {
             None => {
-                unreachable!("Should find minimum if len() >= SAMPLE_SIZE");
+                unreachable!("Should find minimum if len() >= window_size");
             }
             Some(x) => x,
         };
@@ -143,74 +541,130 @@ impl Samples {
         (total / (self.items.len() as f64)) as u64
     }
 
-    fn flush_sqlite(&self, tx: &SqliteTransaction, identifier: &str) {
-        let sql = "INSERT OR REPLACE INTO pessimistic_estimator
-                     (estimate_key, current_value, samples) VALUES (?, ?, ?)";
-        let current_value = u64_to_sql(self.mean()).unwrap_or_else(|_| i64::max_value());
-        tx.execute(
-            sql,
-            rusqlite::params![identifier, current_value, self.to_json()],
-        )
-        .expect("SQLite failure");
-    }
+    /// The `percentile`th (0-100) value of the retained samples: sorts, then
+    ///  linearly interpolates between the two order statistics neighboring
+    ///  `rank = percentile / 100 * (n - 1)`.
+    fn percentile(&self, percentile: u8) -> u64 {
+        if self.items.is_empty() {
+            return 0;
+        }
 
-    fn get_sqlite(conn: &Connection, identifier: &str) -> Samples {
-        let sql = "SELECT samples FROM pessimistic_estimator WHERE estimate_key = ?";
-        conn.query_row(sql, &[identifier], |row| row.get(0))
-            .optional()
-            .expect("SQLite failure")
-            .unwrap_or_else(|| Samples { items: vec![] })
+        let mut sorted = self.items.clone();
+        sorted.sort_unstable();
+        let n = sorted.len();
+        if n == 1 {
+            return sorted[0];
+        }
+
+        let rank = (percentile as f64 / 100.0) * (n as f64 - 1.0);
+        let lower = rank.floor() as usize;
+        let upper = cmp::min(lower + 1, n - 1);
+        let frac = rank - rank.floor();
+        let lower_val = sorted[lower] as f64;
+        let upper_val = sorted[upper] as f64;
+        (lower_val + (upper_val - lower_val) * frac).round() as u64
     }
 
-    fn get_estimate_sqlite(conn: &Connection, identifier: &str) -> Option<u64> {
-        let sql = "SELECT current_value FROM pessimistic_estimator WHERE estimate_key = ?";
-        conn.query_row::<i64, _, _>(sql, &[identifier], |row| row.get(0))
-            .optional()
-            .expect("SQLite failure")
-            .map(|x_i64| {
-                u64::try_from(x_i64).expect("DB corrupt, non-u64-valid estimate was stored")
-            })
+    /// Compute `statistic` over the retained samples.
+    fn estimate(&self, statistic: &EstimationStatistic) -> u64 {
+        match statistic {
+            EstimationStatistic::Mean => self.mean(),
+            EstimationStatistic::Percentile(p) => self.percentile(*p),
+        }
     }
 }
 
-impl PessimisticEstimator {
-    pub fn open(p: &Path, log_error: bool) -> Result<PessimisticEstimator, EstimatorError> {
-        let db = Connection::open_with_flags(p, rusqlite::OpenFlags::SQLITE_OPEN_READ_WRITE)
-            .or_else(|e| {
-                if let SqliteError::SqliteFailure(ref internal, _) = e {
-                    if let rusqlite::ErrorCode::CannotOpen = internal.code {
-                        let mut db = Connection::open(p)?;
-                        let tx = tx_begin_immediate_sqlite(&mut db)?;
-                        PessimisticEstimator::instantiate_db(&tx)?;
-                        tx.commit()?;
-                        Ok(db)
-                    } else {
-                        Err(e)
-                    }
-                } else {
-                    Err(e)
-                }
-            })?;
-        Ok(PessimisticEstimator { db, log_error })
+impl<S: SampleStore + 'static> PessimisticEstimator<S> {
+    /// Constructs a `PessimisticEstimator` backed by `store`, starting its
+    ///  in-memory cache from `initial_cache` and spawning the background
+    ///  `EstimatorService` that owns `store` from then on.
+    pub fn new(
+        store: S,
+        initial_cache: HashMap<String, Samples>,
+        log_error: bool,
+        config: EstimatorConfig,
+    ) -> PessimisticEstimator<S> {
+        let cache = Arc::new(Mutex::new(initial_cache));
+        let (event_sender, event_receiver) = mpsc::channel();
+        let service = EstimatorService {
+            store,
+            cache: cache.clone(),
+            receiver: event_receiver,
+            config,
+        };
+        thread::Builder::new()
+            .name("cost-estimator".into())
+            .spawn(move || service.run())
+            .expect("FATAL: failed to spawn cost estimator service thread");
+
+        PessimisticEstimator {
+            log_error,
+            cache,
+            event_sender,
+            config,
+            _store: std::marker::PhantomData,
+        }
     }
 
-    fn instantiate_db(tx: &SqliteTransaction) -> Result<(), SqliteError> {
-        tx.execute(CREATE_TABLE, rusqlite::NO_PARAMS)?;
-        Ok(())
+    /// Blocks until every `notify_event` call made before this one has been
+    ///  applied to the in-memory cache and flushed to the `SampleStore`. Not
+    ///  used on the chain-processing path -- `notify_event`/`estimate_cost` stay
+    ///  fire-and-forget there -- but lets tests assert against `estimate_cost`
+    ///  or the store right after `notify_event` without racing the background
+    ///  `EstimatorService`.
+    pub fn flush_sync(&self) {
+        let (ack_sender, ack_receiver) = mpsc::channel();
+        if self.event_sender.send(ServiceMessage::Sync(ack_sender)).is_ok() {
+            let _ = ack_receiver.recv();
+        }
     }
+}
 
-    fn get_estimate_key(tx: &TransactionPayload, field: &CostField) -> String {
-        let tx_descriptor = match tx {
-            TransactionPayload::TokenTransfer(..) => "stx-transfer".to_string(),
-            TransactionPayload::ContractCall(cc) => {
-                format!("cc:{}.{}", cc.contract_name, cc.function_name)
-            }
-            TransactionPayload::SmartContract(_sc) => "contract-publish".to_string(),
-            TransactionPayload::PoisonMicroblock(_, _) => "poison-ublock".to_string(),
-            TransactionPayload::Coinbase(_) => "coinbase".to_string(),
-        };
+/// Builds the key used to store/retrieve samples for a given transaction payload
+///  and cost field. The key is namespaced by `epoch_id` so that samples gathered
+///  under one cost schedule never get averaged together with samples gathered
+///  under another: a fresh epoch starts with a fresh, empty sample set.
+fn get_estimate_key(tx: &TransactionPayload, field: &CostField, epoch_id: &StacksEpochId) -> String {
+    let tx_descriptor = match tx {
+        TransactionPayload::TokenTransfer(..) => "stx-transfer".to_string(),
+        TransactionPayload::ContractCall(cc) => {
+            format!("cc:{}.{}", cc.contract_name, cc.function_name)
+        }
+        TransactionPayload::SmartContract(_sc) => "contract-publish".to_string(),
+        TransactionPayload::PoisonMicroblock(_, _) => "poison-ublock".to_string(),
+        TransactionPayload::Coinbase(_) => "coinbase".to_string(),
+    };
+
+    format!("{}:{}:{}", epoch_id, &tx_descriptor, field)
+}
+
+impl PessimisticEstimator<SqliteSampleStore> {
+    /// Opens (creating if necessary) a `pessimistic_estimator.sqlite` file at `p`
+    ///  and returns an estimator backed by it, configured per `config`.
+    pub fn open(
+        p: &Path,
+        log_error: bool,
+        config: EstimatorConfig,
+    ) -> Result<PessimisticEstimator<SqliteSampleStore>, EstimatorError> {
+        let store = SqliteSampleStore::open(p, config.statistic)?;
+        let initial_cache = store.load_all()?;
+        Ok(PessimisticEstimator::new(store, initial_cache, log_error, config))
+    }
+}
 
-        format!("{}:{}", &tx_descriptor, field)
+impl PessimisticEstimator<MemorySampleStore> {
+    /// Constructs an estimator backed purely by an in-memory store, with no
+    ///  persistence across restarts. Intended for tests.
+    pub fn new_in_memory(
+        log_error: bool,
+        config: EstimatorConfig,
+    ) -> PessimisticEstimator<MemorySampleStore> {
+        PessimisticEstimator::new(
+            MemorySampleStore::new(config.statistic),
+            HashMap::new(),
+            log_error,
+            config,
+        )
     }
 }
 
@@ -220,71 +674,69 @@ impl From<SqliteError> for EstimatorError {
     }
 }
 
-impl CostEstimator for PessimisticEstimator {
+impl<S: SampleStore + 'static> CostEstimator for PessimisticEstimator<S> {
     fn notify_event(
         &mut self,
         tx: &TransactionPayload,
         actual_cost: &ExecutionCost,
+        evaluated_epoch: &StacksEpochId,
     ) -> Result<(), EstimatorError> {
         if false {
             // only log the estimate error if an estimate could be constructed
-            if let Ok(estimated_cost) = self.estimate_cost(tx) {
+            if let Ok(estimated_cost) = self.estimate_cost(tx, evaluated_epoch) {
                 let estimated_scalar = estimated_cost
                     .proportion_dot_product(&BLOCK_LIMIT_MAINNET, PROPORTION_RESOLUTION);
                 let actual_scalar =
                     actual_cost.proportion_dot_product(&BLOCK_LIMIT_MAINNET, PROPORTION_RESOLUTION);
                 info!("PessimisticEstimator received event";
-                      "key" => %PessimisticEstimator::get_estimate_key(tx, &CostField::RuntimeCost),
+                      "key" => %get_estimate_key(tx, &CostField::RuntimeCost, evaluated_epoch),
                       "estimate" => estimated_scalar,
                       "actual" => actual_scalar,
                       "estimate_err" => (estimated_scalar as i64 - actual_scalar as i64),
                       "estimate_err_pct" => (estimated_scalar as i64 - actual_scalar as i64)/(cmp::max(1, actual_scalar as i64)),);
                 for field in CostField::ALL.iter() {
                     info!("New data event received";
-                          "key" => %PessimisticEstimator::get_estimate_key(tx, field),
+                          "key" => %get_estimate_key(tx, field, evaluated_epoch),
                           "value" => field.select_key(actual_cost));
                 }
             }
         }
 
-        let sql_tx = tx_begin_immediate_sqlite(&mut self.db)?;
-        for field in CostField::ALL.iter() {
-            let key = PessimisticEstimator::get_estimate_key(tx, field);
-            let field_cost = field.select_key(actual_cost);
-            let mut current_sample = Samples::get_sqlite(&sql_tx, &key);
-            current_sample.update_with(field_cost);
-            current_sample.flush_sqlite(&sql_tx, &key);
+        // Hand the observation off to the background service and return immediately:
+        //  the chain-processing thread never waits on a store write.
+        let event = EstimatorEvent {
+            tx: tx.clone(),
+            actual_cost: actual_cost.clone(),
+            evaluated_epoch: evaluated_epoch.clone(),
+        };
+        if let Err(e) = self.event_sender.send(ServiceMessage::Event(event)) {
+            warn!("Failed to send event to cost estimator service"; "error" => %e);
         }
-        sql_tx.commit()?;
         Ok(())
     }
 
-    fn estimate_cost(&self, tx: &TransactionPayload) -> Result<ExecutionCost, EstimatorError> {
-        let runtime = Samples::get_estimate_sqlite(
-            &self.db,
-            &PessimisticEstimator::get_estimate_key(tx, &CostField::RuntimeCost),
-        )
-        .ok_or_else(|| EstimatorError::NoEstimateAvailable)?;
-        let read_count = Samples::get_estimate_sqlite(
-            &self.db,
-            &PessimisticEstimator::get_estimate_key(tx, &CostField::ReadCount),
-        )
-        .ok_or_else(|| EstimatorError::NoEstimateAvailable)?;
-        let read_length = Samples::get_estimate_sqlite(
-            &self.db,
-            &PessimisticEstimator::get_estimate_key(tx, &CostField::ReadLength),
-        )
-        .ok_or_else(|| EstimatorError::NoEstimateAvailable)?;
-        let write_count = Samples::get_estimate_sqlite(
-            &self.db,
-            &PessimisticEstimator::get_estimate_key(tx, &CostField::WriteCount),
-        )
-        .ok_or_else(|| EstimatorError::NoEstimateAvailable)?;
-        let write_length = Samples::get_estimate_sqlite(
-            &self.db,
-            &PessimisticEstimator::get_estimate_key(tx, &CostField::WriteLength),
-        )
-        .ok_or_else(|| EstimatorError::NoEstimateAvailable)?;
+    fn estimate_cost(
+        &self,
+        tx: &TransactionPayload,
+        evaluated_epoch: &StacksEpochId,
+    ) -> Result<ExecutionCost, EstimatorError> {
+        let cache = self.cache.lock().expect("estimator cache lock poisoned");
+        let get_field = |field: &CostField| -> Option<u64> {
+            cache
+                .get(&get_estimate_key(tx, field, evaluated_epoch))
+                .map(|samples| samples.estimate(&self.config.statistic))
+        };
+
+        let runtime =
+            get_field(&CostField::RuntimeCost).ok_or_else(|| EstimatorError::NoEstimateAvailable)?;
+        let read_count =
+            get_field(&CostField::ReadCount).ok_or_else(|| EstimatorError::NoEstimateAvailable)?;
+        let read_length =
+            get_field(&CostField::ReadLength).ok_or_else(|| EstimatorError::NoEstimateAvailable)?;
+        let write_count =
+            get_field(&CostField::WriteCount).ok_or_else(|| EstimatorError::NoEstimateAvailable)?;
+        let write_length =
+            get_field(&CostField::WriteLength).ok_or_else(|| EstimatorError::NoEstimateAvailable)?;
 
         Ok(ExecutionCost {
             runtime,
@@ -295,3 +747,140 @@ impl CostEstimator for PessimisticEstimator {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unique_temp_db_path(name: &str) -> std::path::PathBuf {
+        let mut path = std::env::temp_dir();
+        path.push(format!(
+            "pessimistic_estimator_test_{}_{}.sqlite",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn percentile_interpolates_over_sorted_samples() {
+        let samples = Samples {
+            items: vec![50, 10, 30, 40, 20],
+        };
+        assert_eq!(samples.percentile(0), 10);
+        assert_eq!(samples.percentile(50), 30);
+        assert_eq!(samples.percentile(100), 50);
+        // rank = 0.9 * 4 = 3.6 -> interpolates 60% of the way from the 4th
+        //  order statistic (40) to the 5th (50).
+        assert_eq!(samples.percentile(90), 46);
+    }
+
+    #[test]
+    fn percentile_of_empty_samples_is_zero() {
+        let samples = Samples { items: vec![] };
+        assert_eq!(samples.percentile(90), 0);
+    }
+
+    #[test]
+    fn update_with_respects_the_configured_window_size() {
+        let mut samples = Samples { items: vec![] };
+        for value in &[1, 2, 3] {
+            assert!(samples.update_with(*value, 3));
+        }
+
+        // Window is full: a sample no greater than the current min is dropped.
+        assert!(!samples.update_with(1, 3));
+        assert_eq!(samples.items.len(), 3);
+
+        // A sample greater than the current min replaces it rather than growing
+        //  the set past `window_size`.
+        assert!(samples.update_with(10, 3));
+        assert_eq!(samples.items.len(), 3);
+        assert!(!samples.items.contains(&1));
+    }
+
+    #[test]
+    fn update_with_shrinks_a_warm_loaded_set_to_a_lowered_window() {
+        // Simulates a set persisted under window_size = 5, then warm-loaded
+        //  after the operator lowered the configured window to 3.
+        let mut samples = Samples {
+            items: vec![10, 20, 30, 40, 50],
+        };
+        samples.update_with(25, 3);
+        assert_eq!(samples.items.len(), 3);
+        // Only the 3 highest of the original 5 survive the shrink.
+        let mut items = samples.items.clone();
+        items.sort_unstable();
+        assert_eq!(items, vec![30, 40, 50]);
+    }
+
+    #[test]
+    fn flush_only_writes_keys_that_actually_changed() {
+        let store = MemorySampleStore::new(EstimationStatistic::Mean);
+        let mut service = EstimatorService {
+            store,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            receiver: mpsc::channel().1,
+            config: EstimatorConfig {
+                window_size: 2,
+                statistic: EstimationStatistic::Mean,
+            },
+        };
+
+        let mut dirty = HashSet::new();
+        service.apply_sample("k".to_string(), 1, &mut dirty);
+        assert!(dirty.contains("k"));
+
+        service.flush(&mut dirty);
+        assert!(dirty.is_empty());
+        assert_eq!(service.store.load_all().unwrap()["k"].items, vec![1]);
+
+        // The window (size 2) is not yet full, so a second, smaller sample is
+        //  still accepted and reported dirty...
+        service.apply_sample("k".to_string(), 0, &mut dirty);
+        assert!(dirty.contains("k"));
+        service.flush(&mut dirty);
+
+        // ...but once full, a sample no greater than the current min changes
+        //  nothing and must not be flushed.
+        service.apply_sample("k".to_string(), 0, &mut dirty);
+        assert!(dirty.is_empty());
+    }
+
+    #[test]
+    fn flush_sync_round_trips_through_the_background_service() {
+        let estimator = PessimisticEstimator::new_in_memory(false, EstimatorConfig::default());
+        // With no events pending, the Sync message should still be acked once the
+        //  service thread picks it up -- this is just the wiring, exercised
+        //  without needing a real TransactionPayload.
+        estimator.flush_sync();
+    }
+
+    #[test]
+    fn migrating_a_pre_schema_version_database_preserves_existing_rows() {
+        let path = unique_temp_db_path("migration");
+        {
+            // Simulate a database created before the migration framework existed:
+            //  the `pessimistic_estimator` table is present, but `schema_version`
+            //  is not.
+            let db = Connection::open(&path).expect("failed to create pre-migration db");
+            db.execute(CREATE_TABLE, rusqlite::NO_PARAMS).unwrap();
+            db.execute(
+                "INSERT INTO pessimistic_estimator (estimate_key, current_value, samples) \
+                 VALUES (?, ?, ?)",
+                rusqlite::params!["pre-existing", 42i64, "[42]"],
+            )
+            .unwrap();
+        }
+
+        let store = SqliteSampleStore::open(&path, EstimationStatistic::Mean)
+            .expect("opening a pre-migration-framework database should not error");
+        let cache = store
+            .load_all()
+            .expect("load_all should succeed once migrated");
+        assert_eq!(cache.get("pre-existing").unwrap().items, vec![42]);
+
+        let _ = std::fs::remove_file(&path);
+    }
+}